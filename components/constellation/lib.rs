@@ -0,0 +1,10 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! The constellation: owns the pipeline hierarchy and routes cross-pipeline
+//! concerns like media session arbitration. This snapshot only carries the
+//! media session arbiter; the `Constellation` struct itself, its event
+//! loop, and its `Cargo.toml` live outside this chunk.
+
+pub mod media_session_arbiter;
@@ -0,0 +1,138 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Arbitrates which pipeline's `MediaSession` owns the OS media keys when
+//! more than one pipeline is producing audio at once, per
+//! https://w3c.github.io/mediasession/#active-media-session.
+//!
+//! This only tracks the ordered set of candidate pipelines and decides
+//! which one is active; it doesn't dispatch `notify_active_state_changed`
+//! to script threads itself. The `Constellation` struct and its
+//! `ConstellationControlMsg` dispatch loop aren't part of this source
+//! snapshot, so wiring `MediaSessionArbiter` into the real constellation
+//! (calling `register`/`unregister` as pipelines start/stop producing
+//! audio or close, and sending the resulting active-state changes down to
+//! script) is left for the surrounding code to do once that lands.
+
+use msg::constellation_msg::PipelineId;
+
+#[cfg(test)]
+use msg::constellation_msg::{PipelineNamespace, PipelineNamespaceId};
+
+/// Tracks which pipelines are currently eligible to be the active media
+/// session, and which one of them actually is.
+///
+/// Candidates are kept in registration order; the most recently registered
+/// candidate is active. This matches the common case of a newly-started
+/// playback session taking over the media keys, while still letting an
+/// older session regain them if the active one pauses or its pipeline
+/// closes.
+#[derive(Default)]
+pub struct MediaSessionArbiter {
+    candidates: Vec<PipelineId>,
+}
+
+impl MediaSessionArbiter {
+    pub fn new() -> MediaSessionArbiter {
+        MediaSessionArbiter {
+            candidates: Vec::new(),
+        }
+    }
+
+    /// The pipeline that should currently be routed OS media key commands,
+    /// if any pipeline is eligible.
+    pub fn active(&self) -> Option<PipelineId> {
+        self.candidates.last().copied()
+    }
+
+    /// Register `pipeline` as eligible to be the active media session,
+    /// e.g. because it started playing audio. Re-registering an already
+    /// known pipeline moves it to the end of the queue, making it active
+    /// again. Returns the
+    /// active pipeline after the update, for the caller to notify.
+    pub fn register(&mut self, pipeline: PipelineId) -> Option<PipelineId> {
+        self.candidates.retain(|&candidate| candidate != pipeline);
+        self.candidates.push(pipeline);
+        self.active()
+    }
+
+    /// Remove `pipeline` from the candidate set, e.g. because it paused or
+    /// its pipeline closed. Returns the active pipeline after the update
+    /// (which may be a newly promoted candidate, or `None` if the set is
+    /// now empty), for the caller to notify.
+    pub fn unregister(&mut self, pipeline: PipelineId) -> Option<PipelineId> {
+        self.candidates.retain(|&candidate| candidate != pipeline);
+        self.active()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_pipeline_id() -> PipelineId {
+        // Install the namespace only once per test thread, so each call
+        // hands out a fresh id from the same namespace.
+        thread_local!(static NAMESPACE_INSTALLED: std::cell::Cell<bool> = std::cell::Cell::new(false));
+        NAMESPACE_INSTALLED.with(|installed| {
+            if !installed.get() {
+                PipelineNamespace::install(PipelineNamespaceId(0));
+                installed.set(true);
+            }
+        });
+        PipelineId::new()
+    }
+
+    #[test]
+    fn most_recently_registered_candidate_is_active() {
+        let mut arbiter = MediaSessionArbiter::new();
+        let first = new_pipeline_id();
+        let second = new_pipeline_id();
+
+        assert_eq!(arbiter.register(first), Some(first));
+        assert_eq!(arbiter.register(second), Some(second));
+    }
+
+    #[test]
+    fn unregistering_the_active_candidate_promotes_the_next_one() {
+        let mut arbiter = MediaSessionArbiter::new();
+        let first = new_pipeline_id();
+        let second = new_pipeline_id();
+        arbiter.register(first);
+        arbiter.register(second);
+
+        assert_eq!(arbiter.unregister(second), Some(first));
+    }
+
+    #[test]
+    fn unregistering_a_non_active_candidate_keeps_the_active_one() {
+        let mut arbiter = MediaSessionArbiter::new();
+        let first = new_pipeline_id();
+        let second = new_pipeline_id();
+        arbiter.register(first);
+        arbiter.register(second);
+
+        assert_eq!(arbiter.unregister(first), Some(second));
+    }
+
+    #[test]
+    fn unregistering_the_only_candidate_leaves_no_active_session() {
+        let mut arbiter = MediaSessionArbiter::new();
+        let only = new_pipeline_id();
+        arbiter.register(only);
+
+        assert_eq!(arbiter.unregister(only), None);
+    }
+
+    #[test]
+    fn re_registering_a_candidate_reclaims_active_status() {
+        let mut arbiter = MediaSessionArbiter::new();
+        let first = new_pipeline_id();
+        let second = new_pipeline_id();
+        arbiter.register(first);
+        arbiter.register(second);
+
+        assert_eq!(arbiter.register(first), Some(first));
+    }
+}
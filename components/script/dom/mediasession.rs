@@ -5,11 +5,13 @@
 use crate::dom::bindings::callback::ExceptionHandling;
 use crate::dom::bindings::cell::DomRefCell;
 use crate::dom::bindings::codegen::Bindings::HTMLMediaElementBinding::HTMLMediaElementMethods;
+use crate::dom::bindings::codegen::Bindings::MediaMetadataBinding::MediaImageInit;
 use crate::dom::bindings::codegen::Bindings::MediaMetadataBinding::MediaMetadataInit;
 use crate::dom::bindings::codegen::Bindings::MediaMetadataBinding::MediaMetadataMethods;
 use crate::dom::bindings::codegen::Bindings::MediaSessionBinding;
 use crate::dom::bindings::codegen::Bindings::MediaSessionBinding::MediaPositionState;
 use crate::dom::bindings::codegen::Bindings::MediaSessionBinding::MediaSessionAction;
+use crate::dom::bindings::codegen::Bindings::MediaSessionBinding::MediaSessionActionDetails as JSMediaSessionActionDetails;
 use crate::dom::bindings::codegen::Bindings::MediaSessionBinding::MediaSessionActionHandler;
 use crate::dom::bindings::codegen::Bindings::MediaSessionBinding::MediaSessionMethods;
 use crate::dom::bindings::codegen::Bindings::MediaSessionBinding::MediaSessionPlaybackState;
@@ -17,19 +19,101 @@ use crate::dom::bindings::error::{Error, Fallible};
 use crate::dom::bindings::num::Finite;
 use crate::dom::bindings::reflector::{reflect_dom_object, DomObject, Reflector};
 use crate::dom::bindings::root::{DomRoot, MutNullableDom};
-use crate::dom::bindings::str::DOMString;
+use crate::dom::bindings::str::{DOMString, USVString};
 use crate::dom::htmlmediaelement::HTMLMediaElement;
 use crate::dom::mediametadata::MediaMetadata;
 use crate::dom::window::Window;
 use crate::realms::{AlreadyInRealm, InRealm};
 use dom_struct::dom_struct;
+use embedder_traits::MediaImage as EmbedderMediaImage;
+use embedder_traits::MediaImageSize;
 use embedder_traits::MediaMetadata as EmbedderMediaMetadata;
 use embedder_traits::MediaSessionEvent;
+use script_traits::MediaSessionActionDetails;
 use script_traits::MediaSessionActionType;
 use script_traits::ScriptMsg;
 use std::collections::HashMap;
 use std::rc::Rc;
 
+/// Parse a `sizes` attribute value (e.g. `"96x96 128x128"` or `"any"`) into
+/// the structured sizes the embedder works with, per
+/// https://html.spec.whatwg.org/multipage/links.html#attr-link-sizes.
+fn parse_media_image_sizes(value: &str) -> Vec<MediaImageSize> {
+    value
+        .split_whitespace()
+        .filter_map(|token| {
+            if token.eq_ignore_ascii_case("any") {
+                return Some(MediaImageSize::Any);
+            }
+            let mut dimensions = token.splitn(2, |c| c == 'x' || c == 'X');
+            let width = dimensions.next()?.parse().ok()?;
+            let height = dimensions.next()?.parse().ok()?;
+            Some(MediaImageSize::Fixed(width, height))
+        })
+        .collect()
+}
+
+/// The inverse of `parse_media_image_sizes`, used by `GetMetadata` to
+/// round-trip the artwork list back out as a `sizes` string.
+fn serialize_media_image_sizes(sizes: &[MediaImageSize]) -> String {
+    sizes
+        .iter()
+        .map(|size| match size {
+            MediaImageSize::Any => "any".to_owned(),
+            MediaImageSize::Fixed(width, height) => format!("{}x{}", width, height),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A snapshot of the session's "official playback position", per
+/// https://w3c.github.io/mediasession/#dom-mediasession-setpositionstate.
+/// `current_position` extrapolates from this snapshot so pages and the
+/// embedder don't need to poll `setPositionState` every frame.
+#[derive(Clone, Copy, Debug, JSTraceable, MallocSizeOf)]
+struct PositionState {
+    duration: f64,
+    playback_rate: f64,
+    last_reported_position: f64,
+    /// The monotonic time, in seconds, at which `last_reported_position`
+    /// was last refreshed.
+    position_updated_time: f64,
+}
+
+/// A monotonic clock, in seconds, used to extrapolate playback position
+/// between `setPositionState` calls.
+fn monotonic_now() -> f64 {
+    time::precise_time_s()
+}
+
+/// The default step, in seconds, used by the `seekbackward`/`seekforward`
+/// default handlers when the action doesn't specify a `seekOffset`.
+const DEFAULT_SEEK_OFFSET: f64 = 10.0;
+
+/// Extrapolate a playback position `elapsed` seconds after it was last
+/// reported, clamped to `[0, duration]`. Pulled out of `current_position`
+/// so the extrapolation math can be unit tested without a `MediaSession`.
+fn extrapolate_position(
+    last_reported_position: f64,
+    elapsed: f64,
+    playback_rate: f64,
+    duration: f64,
+) -> f64 {
+    let position = last_reported_position + elapsed * playback_rate;
+    position.max(0.).min(duration)
+}
+
+/// Clamp a seek target to `[0, duration]`, treating a non-finite duration
+/// (e.g. a live stream, or metadata not yet loaded) as unbounded.
+fn clamp_seek_target(time: f64, duration: f64) -> f64 {
+    let upper_bound = if duration.is_finite() {
+        duration
+    } else {
+        f64::MAX
+    };
+    time.max(0.).min(upper_bound)
+}
+
 #[dom_struct]
 pub struct MediaSession {
     reflector_: Reflector,
@@ -44,6 +128,19 @@ pub struct MediaSession {
     /// The media instance controlled by this media session.
     /// For now only HTMLMediaElements are controlled by media sessions.
     media_instance: MutNullableDom<HTMLMediaElement>,
+    /// https://w3c.github.io/mediasession/#dom-mediasession-setpositionstate
+    position_state: DomRefCell<Option<PositionState>>,
+    /// Whether the constellation has designated this as *the* active media
+    /// session, i.e. the one OS media keys and `handle_action` commands are
+    /// routed to. See https://w3c.github.io/mediasession/#active-media-session.
+    ///
+    /// Defaults to `true`: a session is usually the only one around, and
+    /// cross-pipeline arbitration (the constellation's
+    /// `MediaSessionArbiter`, via `notify_active_state_changed`) only needs
+    /// to demote the session, not opt it in, once a pipeline starts
+    /// producing audio elsewhere. This keeps `handle_action` working for
+    /// the common single-session case instead of rejecting everything.
+    is_active: DomRefCell<bool>,
 }
 
 impl MediaSession {
@@ -55,6 +152,8 @@ impl MediaSession {
             playback_state: DomRefCell::new(MediaSessionPlaybackState::None),
             action_handlers: DomRefCell::new(HashMap::new()),
             media_instance: Default::default(),
+            position_state: DomRefCell::new(None),
+            is_active: DomRefCell::new(true),
         };
         media_session
     }
@@ -71,11 +170,46 @@ impl MediaSession {
         self.media_instance.set(Some(media_instance));
     }
 
-    pub fn handle_action(&self, action: MediaSessionActionType) {
-        debug!("Handle media session action {:?}", action);
+    /// Whether the constellation currently considers this the single
+    /// active media session.
+    pub fn is_active(&self) -> bool {
+        *self.is_active.borrow()
+    }
+
+    /// Called by the constellation when this session gains or loses active
+    /// status, e.g. because another session started playing or this one's
+    /// pipeline was promoted after the previous active session closed.
+    ///
+    /// Losing active status only stops this session from receiving routed
+    /// OS media key commands (see the `is_active` check in `handle_action`);
+    /// it does not pause the underlying media. A session that's merely not
+    /// the one owning the hardware keys can keep playing in the background.
+    pub fn notify_active_state_changed(&self, active: bool) {
+        *self.is_active.borrow_mut() = active;
+    }
 
-        if let Some(handler) = self.action_handlers.borrow().get(&action) {
-            if handler.Call__(ExceptionHandling::Report).is_err() {
+    pub fn handle_action(&self, details: MediaSessionActionDetails) {
+        if !self.is_active() {
+            debug!(
+                "Ignoring media session action {:?}: session is not active",
+                details.action
+            );
+            return;
+        }
+
+        debug!("Handle media session action {:?}", details.action);
+
+        if let Some(handler) = self.action_handlers.borrow().get(&details.action) {
+            // The JS-facing callback takes the WebIDL dictionary, not the
+            // internal `script_traits` type shared with the constellation
+            // and the embedder's OS media key integration.
+            let js_details = JSMediaSessionActionDetails {
+                action: details.action.into(),
+                seekOffset: details.seek_offset.map(Finite::wrap),
+                seekTime: details.seek_time.map(Finite::wrap),
+                fastSeek: details.fast_seek,
+            };
+            if handler.Call__(&js_details, ExceptionHandling::Report).is_err() {
                 warn!("Error calling MediaSessionActionHandler callback");
             }
             return;
@@ -83,25 +217,66 @@ impl MediaSession {
 
         // Default action.
         if let Some(media) = self.media_instance.get() {
-            match action {
+            match details.action {
                 MediaSessionActionType::Play => {
                     let in_realm_proof = AlreadyInRealm::assert(&self.global());
                     media.Play(InRealm::Already(&in_realm_proof));
+                    self.refresh_position_state();
                 },
                 MediaSessionActionType::Pause => {
                     media.Pause();
+                    self.refresh_position_state();
+                },
+                MediaSessionActionType::SeekBackward => {
+                    let offset = details.seek_offset.unwrap_or(DEFAULT_SEEK_OFFSET);
+                    self.seek_by(&media, -offset);
+                },
+                MediaSessionActionType::SeekForward => {
+                    let offset = details.seek_offset.unwrap_or(DEFAULT_SEEK_OFFSET);
+                    self.seek_by(&media, offset);
+                },
+                MediaSessionActionType::SeekTo => {
+                    if let Some(seek_time) = details.seek_time {
+                        self.seek_to(&media, seek_time, details.fast_seek);
+                    }
                 },
-                MediaSessionActionType::SeekBackward => {},
-                MediaSessionActionType::SeekForward => {},
                 MediaSessionActionType::PreviousTrack => {},
                 MediaSessionActionType::NextTrack => {},
                 MediaSessionActionType::SkipAd => {},
-                MediaSessionActionType::Stop => {},
-                MediaSessionActionType::SeekTo => {},
+                MediaSessionActionType::Stop => {
+                    media.Pause();
+                    media.SetCurrentTime(Finite::wrap(0.0));
+                    *self.position_state.borrow_mut() = None;
+                },
             }
         }
     }
 
+    /// Move `currentTime` by `offset` seconds (negative for backward),
+    /// clamped to `[0, duration]`. Used as the default handler for
+    /// `seekbackward`/`seekforward` when the page hasn't registered one.
+    fn seek_by(&self, media: &HTMLMediaElement, offset: f64) {
+        let target = Self::clamp_to_duration(media, *media.CurrentTime() + offset);
+        media.SetCurrentTime(Finite::wrap(target));
+        self.refresh_position_state();
+    }
+
+    /// Jump to an absolute `seekTime`, clamped to `[0, duration]`. Used as
+    /// the default handler for `seekto`.
+    fn seek_to(&self, media: &HTMLMediaElement, seek_time: f64, fast_seek: bool) {
+        let target = Self::clamp_to_duration(media, seek_time);
+        if fast_seek {
+            let _ = media.FastSeek(Finite::wrap(target));
+        } else {
+            media.SetCurrentTime(Finite::wrap(target));
+        }
+        self.refresh_position_state();
+    }
+
+    fn clamp_to_duration(media: &HTMLMediaElement, time: f64) -> f64 {
+        clamp_seek_target(time, media.Duration())
+    }
+
     pub fn send_event(&self, event: MediaSessionEvent) {
         let global = self.global();
         let window = global.as_window();
@@ -109,6 +284,67 @@ impl MediaSession {
         window.send_to_constellation(ScriptMsg::MediaSessionEvent(pipeline_id, event));
     }
 
+    /// Store a new position snapshot and notify the embedder, refreshing
+    /// the "last position updated time" used by `current_position`.
+    fn update_position_state(&self, duration: f64, position: f64, playback_rate: f64) {
+        *self.position_state.borrow_mut() = Some(PositionState {
+            duration,
+            playback_rate,
+            last_reported_position: position,
+            position_updated_time: monotonic_now(),
+        });
+        // The snapshot was just taken, so `current_position` here is the
+        // reported position clamped to `[0, duration]`; the embedder gets
+        // the same value `current_position` will extrapolate from.
+        self.send_event(MediaSessionEvent::SetPositionState(
+            duration,
+            self.current_position(),
+            playback_rate,
+        ));
+    }
+
+    /// Refresh the stored position snapshot from the controlled media
+    /// element's current state. Should be called on play/pause and on
+    /// rate/seek changes so `current_position` stays accurate between
+    /// explicit `setPositionState` calls.
+    pub fn refresh_position_state(&self) {
+        let duration = match self.position_state.borrow().as_ref() {
+            Some(state) => state.duration,
+            None => return,
+        };
+        if let Some(media) = self.media_instance.get() {
+            let position = *media.CurrentTime();
+            // While paused, the effective rate is 0 regardless of the
+            // `playbackRate` attribute, otherwise `current_position` would
+            // keep extrapolating forward after playback has stopped.
+            let playback_rate = if media.Paused() {
+                0.
+            } else {
+                *media.PlaybackRate()
+            };
+            self.update_position_state(duration, position, playback_rate);
+        }
+    }
+
+    /// https://w3c.github.io/mediasession/#current-playback-position
+    ///
+    /// Extrapolate the current playback position from the last reported
+    /// position, so the embedder's scrubber reflects accurate progress
+    /// without the page re-calling `setPositionState` every frame.
+    pub fn current_position(&self) -> f64 {
+        let state = match *self.position_state.borrow() {
+            Some(state) => state,
+            None => return 0.,
+        };
+        let elapsed = monotonic_now() - state.position_updated_time;
+        extrapolate_position(
+            state.last_reported_position,
+            elapsed,
+            state.playback_rate,
+            state.duration,
+        )
+    }
+
     pub fn update_title(&self, title: String) {
         let mut metadata = self.metadata.borrow_mut();
         if let Some(ref mut metadata) = *metadata {
@@ -135,6 +371,15 @@ impl MediaSessionMethods for MediaSession {
             init.title = DOMString::from_string(metadata.title.clone());
             init.artist = DOMString::from_string(metadata.artist.clone());
             init.album = DOMString::from_string(metadata.album.clone());
+            init.artwork = metadata
+                .artwork
+                .iter()
+                .map(|image| MediaImageInit {
+                    src: USVString(image.src.clone()),
+                    sizes: DOMString::from_string(serialize_media_image_sizes(&image.sizes)),
+                    type_: DOMString::from_string(image.type_.clone()),
+                })
+                .collect();
             let global = self.global();
             Some(MediaMetadata::new(&global.as_window(), &init))
         } else {
@@ -157,10 +402,27 @@ impl MediaSessionMethods for MediaSession {
                 } else {
                     m.Title().into()
                 };
+                let base_url = window.Document().base_url();
+                let artwork = m
+                    .Artwork()
+                    .into_iter()
+                    .map(|image| {
+                        let src = base_url
+                            .join(&image.src)
+                            .map(|url| url.into_string())
+                            .unwrap_or_else(|_| image.src.to_string());
+                        EmbedderMediaImage {
+                            src,
+                            sizes: parse_media_image_sizes(&image.sizes),
+                            type_: image.type_.to_string(),
+                        }
+                    })
+                    .collect();
                 EmbedderMediaMetadata {
                     title,
                     artist: m.Artist().into(),
                     album: m.Album().into(),
+                    artwork,
                 }
             },
             None => EmbedderMediaMetadata::new(window.get_url().into_string()),
@@ -179,6 +441,15 @@ impl MediaSessionMethods for MediaSession {
     /// https://w3c.github.io/mediasession/#dom-mediasession-playbackstate
     fn SetPlaybackState(&self, state: MediaSessionPlaybackState) {
         *self.playback_state.borrow_mut() = state;
+        self.refresh_position_state();
+
+        // Register (or withdraw) this session as a candidate for the single
+        // active media session; the constellation arbitrates which pipeline
+        // actually receives OS media key commands. See
+        // https://w3c.github.io/mediasession/#active-media-session.
+        self.send_event(MediaSessionEvent::PlaybackStateChange(
+            state == MediaSessionPlaybackState::Playing,
+        ));
     }
 
     /// https://w3c.github.io/mediasession/#update-action-handler-algorithm
@@ -203,6 +474,7 @@ impl MediaSessionMethods for MediaSession {
             if let Some(media_instance) = self.media_instance.get() {
                 media_instance.reset();
             }
+            *self.position_state.borrow_mut() = None;
             return Ok(());
         }
 
@@ -240,15 +512,20 @@ impl MediaSessionMethods for MediaSession {
         }
 
         // Update the position state and last position updated time.
+        let duration = state.duration.map(|v| *v).unwrap();
+        // If the playbackRate is not present or its value is null, set it to 1.0.
+        let playback_rate = state.playbackRate.map(|v| *v).unwrap_or(1.0);
+        // If the position is not present or its value is null, set it to zero.
+        let position = state.position.map(|v| *v).unwrap_or(0.0);
+
         if let Some(media_instance) = self.media_instance.get() {
-            media_instance.set_duration(state.duration.map(|v| *v).unwrap());
-            // If the playbackRate is not present or its value is null, set it to 1.0.
-            let _ =
-                media_instance.SetPlaybackRate(state.playbackRate.unwrap_or(Finite::wrap(1.0)))?;
-            // If the position is not present or its value is null, set it to zero.
-            media_instance.SetCurrentTime(state.position.unwrap_or(Finite::wrap(0.0)));
+            media_instance.set_duration(duration);
+            let _ = media_instance.SetPlaybackRate(Finite::wrap(playback_rate))?;
+            media_instance.SetCurrentTime(Finite::wrap(position));
         }
 
+        self.update_position_state(duration, position, playback_rate);
+
         Ok(())
     }
 }
@@ -268,3 +545,78 @@ impl From<MediaSessionAction> for MediaSessionActionType {
         }
     }
 }
+
+impl From<MediaSessionActionType> for MediaSessionAction {
+    fn from(action: MediaSessionActionType) -> MediaSessionAction {
+        match action {
+            MediaSessionActionType::Play => MediaSessionAction::Play,
+            MediaSessionActionType::Pause => MediaSessionAction::Pause,
+            MediaSessionActionType::SeekBackward => MediaSessionAction::Seekbackward,
+            MediaSessionActionType::SeekForward => MediaSessionAction::Seekforward,
+            MediaSessionActionType::PreviousTrack => MediaSessionAction::Previoustrack,
+            MediaSessionActionType::NextTrack => MediaSessionAction::Nexttrack,
+            MediaSessionActionType::SkipAd => MediaSessionAction::Skipad,
+            MediaSessionActionType::Stop => MediaSessionAction::Stop,
+            MediaSessionActionType::SeekTo => MediaSessionAction::Seekto,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_round_trip_fixed_dimensions() {
+        let sizes = parse_media_image_sizes("96x96 128x128");
+        assert_eq!(serialize_media_image_sizes(&sizes), "96x96 128x128");
+    }
+
+    #[test]
+    fn sizes_round_trip_any() {
+        let sizes = parse_media_image_sizes("any");
+        assert_eq!(serialize_media_image_sizes(&sizes), "any");
+    }
+
+    #[test]
+    fn sizes_parsing_is_case_insensitive_and_skips_garbage() {
+        let sizes = parse_media_image_sizes("ANY 64X64 not-a-size");
+        assert_eq!(serialize_media_image_sizes(&sizes), "any 64x64");
+    }
+
+    #[test]
+    fn sizes_parsing_empty_string_yields_no_sizes() {
+        assert!(parse_media_image_sizes("").is_empty());
+    }
+
+    #[test]
+    fn extrapolation_advances_with_elapsed_time() {
+        assert_eq!(extrapolate_position(10., 5., 1.0, 60.), 15.);
+    }
+
+    #[test]
+    fn extrapolation_is_clamped_to_duration() {
+        assert_eq!(extrapolate_position(55., 10., 1.0, 60.), 60.);
+    }
+
+    #[test]
+    fn extrapolation_is_clamped_to_zero() {
+        assert_eq!(extrapolate_position(5., -20., 1.0, 60.), 0.);
+    }
+
+    #[test]
+    fn paused_playback_rate_freezes_extrapolation() {
+        assert_eq!(extrapolate_position(12., 100., 0., 60.), 12.);
+    }
+
+    #[test]
+    fn clamp_seek_target_uses_duration_upper_bound() {
+        assert_eq!(clamp_seek_target(120., 60.), 60.);
+        assert_eq!(clamp_seek_target(-5., 60.), 0.);
+    }
+
+    #[test]
+    fn clamp_seek_target_allows_any_time_for_non_finite_duration() {
+        assert_eq!(clamp_seek_target(1_000_000., f64::NAN), 1_000_000.);
+    }
+}
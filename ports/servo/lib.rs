@@ -0,0 +1,15 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! The `servo` port: embedder-facing glue that isn't specific to any one
+//! windowing toolkit.
+//!
+//! This snapshot only carries the media session bridge; the rest of the
+//! port (window creation, event loop, resource providers, ...) lives
+//! outside this chunk. Its `Cargo.toml` isn't part of this tree either, so
+//! `souvlaki` (the OS media-control crate `media_session` depends on)
+//! still needs to be added there as a dependency before this builds; we
+//! don't fabricate a manifest here per the project's source-snapshot rule.
+
+pub mod media_session;
@@ -0,0 +1,220 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Bridges `MediaSessionEvent`s coming from the constellation to the
+//! platform's now-playing surfaces (Windows SMTC, macOS
+//! `MPNowPlayingInfoCenter`, Linux MPRIS) via the `souvlaki` crate, and
+//! translates OS media key events back into `MediaSessionActionType`
+//! messages for the active session.
+//!
+//! Construct a `MediaSessionController` alongside the top-level window,
+//! call `attach_event_handler` once with the `Sender` half of the channel
+//! the constellation reads `MediaSessionActionDetails` from, and call
+//! `pump_events` with the `Receiver` half of the constellation's
+//! `MediaSessionEvent` channel on each turn of the embedder's main loop.
+
+use embedder_traits::{MediaMetadata, MediaSessionEvent};
+use log::warn;
+use script_traits::{MediaSessionActionDetails, MediaSessionActionType};
+use souvlaki::{
+    MediaControlEvent, MediaControls, MediaMetadata as PlatformMetadata, PlatformConfig,
+    SeekDirection,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Wraps the platform media-control surface used to mirror `MediaSession`
+/// state into the OS now-playing panel. Degrades to an inert no-op
+/// controller when the platform surface can't be created (e.g. no D-Bus
+/// session bus, or a restricted window handle), so headless and sandboxed
+/// runs keep working instead of panicking.
+pub struct MediaSessionController {
+    controls: Option<MediaControls>,
+    /// Mirrors the last `PlaybackStateChange` the constellation sent, so
+    /// `MediaControlEvent::Toggle` (which carries no state of its own) can
+    /// be resolved to the right direction. Shared with the OS event
+    /// callback registered in `attach_event_handler`.
+    is_playing: Arc<AtomicBool>,
+    /// Mirrors the last `SetPositionState` the constellation sent, fed back
+    /// into souvlaki's `MediaPlayback::{Playing,Paused}.progress` so the OS
+    /// scrubber reflects the session's actual position.
+    last_position: Option<Duration>,
+}
+
+impl MediaSessionController {
+    /// Create a controller attached to the given top-level window. `hwnd`
+    /// is only used on Windows; other platforms ignore it.
+    pub fn new(display_name: &str, hwnd: Option<*mut std::ffi::c_void>) -> MediaSessionController {
+        let config = PlatformConfig {
+            dbus_name: "org.servo.Servo",
+            display_name,
+            hwnd,
+        };
+
+        let controls = match MediaControls::new(config) {
+            Ok(controls) => Some(controls),
+            Err(error) => {
+                warn!(
+                    "Could not create native media controls, now-playing integration is disabled: {:?}",
+                    error
+                );
+                None
+            },
+        };
+
+        MediaSessionController {
+            controls,
+            is_playing: Arc::new(AtomicBool::new(false)),
+            last_position: None,
+        }
+    }
+
+    /// Forward inbound OS media key events (play, pause, next/previous
+    /// track, seek, stop) to the active session as `MediaSessionActionType`
+    /// messages.
+    pub fn attach_event_handler(&mut self, sender: Sender<MediaSessionActionDetails>) {
+        let is_playing = self.is_playing.clone();
+        let controls = match self.controls.as_mut() {
+            Some(controls) => controls,
+            None => return,
+        };
+
+        if let Err(error) = controls.attach(move |event| {
+            let details = match media_session_action_from_event(event, &is_playing) {
+                Some(details) => details,
+                None => return,
+            };
+            let _ = sender.send(details);
+        }) {
+            warn!("Could not attach native media control event handler: {:?}", error);
+        }
+    }
+
+    /// Consume a `MediaSessionEvent` and mirror it into the OS now-playing
+    /// panel, if native controls are available.
+    pub fn handle_event(&mut self, event: MediaSessionEvent) {
+        match event {
+            MediaSessionEvent::SetPositionState(_duration, position, _playback_rate) => {
+                self.last_position = Some(Duration::from_secs_f64(position.max(0.)));
+                return self.push_playback_state();
+            },
+            MediaSessionEvent::PlaybackStateChange(playing) => {
+                self.is_playing.store(playing, Ordering::SeqCst);
+                return self.push_playback_state();
+            },
+            MediaSessionEvent::SetMetadata(metadata) => {
+                let controls = match self.controls.as_mut() {
+                    Some(controls) => controls,
+                    None => return,
+                };
+                if let Err(error) = controls.set_metadata(platform_metadata(&metadata)) {
+                    warn!("Could not update native media controls: {:?}", error);
+                }
+            },
+        }
+    }
+
+    /// Push the current `is_playing`/`last_position` snapshot into the OS
+    /// now-playing panel as a single `MediaPlayback` update.
+    fn push_playback_state(&mut self) {
+        let controls = match self.controls.as_mut() {
+            Some(controls) => controls,
+            None => return,
+        };
+
+        let progress = self.last_position.map(souvlaki::MediaPosition);
+        let state = if self.is_playing.load(Ordering::SeqCst) {
+            souvlaki::MediaPlayback::Playing { progress }
+        } else {
+            souvlaki::MediaPlayback::Paused { progress }
+        };
+
+        if let Err(error) = controls.set_playback(state) {
+            warn!("Could not update native media controls: {:?}", error);
+        }
+    }
+
+    /// Drain any `MediaSessionEvent`s the constellation has sent since the
+    /// last call without blocking, mirroring each into the OS now-playing
+    /// panel. Meant to be called once per turn of the embedder's main loop.
+    pub fn pump_events(&mut self, events: &Receiver<MediaSessionEvent>) {
+        loop {
+            match events.try_recv() {
+                Ok(event) => self.handle_event(event),
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+    }
+}
+
+fn platform_metadata(metadata: &MediaMetadata) -> PlatformMetadata {
+    PlatformMetadata {
+        title: Some(&metadata.title),
+        artist: Some(&metadata.artist),
+        album: Some(&metadata.album),
+        cover_url: metadata.artwork.first().map(|image| image.src.as_str()),
+        ..Default::default()
+    }
+}
+
+fn media_session_action_from_event(
+    event: MediaControlEvent,
+    is_playing: &AtomicBool,
+) -> Option<MediaSessionActionDetails> {
+    let no_seek_args = |action| MediaSessionActionDetails {
+        action,
+        seek_offset: None,
+        seek_time: None,
+        fast_seek: false,
+    };
+
+    match event {
+        MediaControlEvent::Play => Some(no_seek_args(MediaSessionActionType::Play)),
+        MediaControlEvent::Pause => Some(no_seek_args(MediaSessionActionType::Pause)),
+        // `Toggle` carries no state of its own: resolve it against the last
+        // `PlaybackStateChange` we mirrored, so the OS toggle key pauses a
+        // playing session instead of always (re-)issuing `Play`.
+        MediaControlEvent::Toggle => {
+            let action = if is_playing.load(Ordering::SeqCst) {
+                MediaSessionActionType::Pause
+            } else {
+                MediaSessionActionType::Play
+            };
+            Some(no_seek_args(action))
+        },
+        MediaControlEvent::Next => Some(no_seek_args(MediaSessionActionType::NextTrack)),
+        MediaControlEvent::Previous => Some(no_seek_args(MediaSessionActionType::PreviousTrack)),
+        MediaControlEvent::Stop => Some(no_seek_args(MediaSessionActionType::Stop)),
+        // `Seek` is a direction with no fixed amount: use the default step
+        // from `MediaSession`'s `seekbackward`/`seekforward` handlers.
+        MediaControlEvent::Seek(direction) => Some(no_seek_args(seek_direction_action(direction))),
+        // `SeekBy` carries a relative amount, so it maps to the same
+        // actions with an explicit `seekOffset`.
+        MediaControlEvent::SeekBy(direction, amount) => Some(MediaSessionActionDetails {
+            action: seek_direction_action(direction),
+            seek_offset: Some(amount.as_secs_f64()),
+            seek_time: None,
+            fast_seek: false,
+        }),
+        // `SetPosition` is an absolute seek, which is what `SeekTo` (not
+        // `SeekForward`/`SeekBackward`) expects.
+        MediaControlEvent::SetPosition(position) => Some(MediaSessionActionDetails {
+            action: MediaSessionActionType::SeekTo,
+            seek_offset: None,
+            seek_time: Some(position.0.as_secs_f64()),
+            fast_seek: false,
+        }),
+        _ => None,
+    }
+}
+
+fn seek_direction_action(direction: SeekDirection) -> MediaSessionActionType {
+    match direction {
+        SeekDirection::Forward => MediaSessionActionType::SeekForward,
+        SeekDirection::Backward => MediaSessionActionType::SeekBackward,
+    }
+}